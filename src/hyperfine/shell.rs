@@ -1,11 +1,111 @@
 use std;
-use std::io;
-use std::process::{Command, ExitStatus, Stdio};
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
 
 use crate::hyperfine::timer::get_cpu_timer;
 
+/// The default grace period given to a child process between sending it
+/// `SIGTERM` / `TerminateProcess` and forcibly killing it with `SIGKILL` /
+/// `TerminateProcess` (forced), once a `--timeout` has elapsed. Overridden
+/// per-command via `CommandOptions::grace_period`.
+const DEFAULT_TIMEOUT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+/// The interval at which a child run with a timeout is polled for exit via
+/// `try_wait`, both before the deadline and during the grace period.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Specifies which shell (if any) is used to invoke a benchmarked command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shell {
+    /// No shell: tokenize the command ourselves (via `shell_words`) and exec
+    /// the resulting program directly.
+    None,
+
+    /// The platform's default shell: `sh -c` on unix, `cmd /C` on Windows.
+    Default,
+
+    /// A user-specified shell, along with the argument(s) it expects before
+    /// the command string, e.g. `program: "pwsh", args: ["-Command"]` or
+    /// `program: "bash", args: ["--norc", "-c"]`.
+    Custom { program: String, args: Vec<String> },
+}
+
+/// Per-command configuration that stays the same across every run of a
+/// benchmarked command.
+#[derive(Debug, Clone)]
+pub struct CommandOptions {
+    /// The shell (if any) used to invoke the command
+    pub shell: Shell,
+
+    /// The maximum amount of time a single run is allowed to take before it
+    /// is killed and marked as timed out
+    pub timeout: Option<Duration>,
+
+    /// How long a timed-out child is given to exit after `SIGTERM` /
+    /// `TerminateProcess` before being forcibly killed with `SIGKILL` /
+    /// `TerminateProcess` (forced)
+    pub grace_period: Duration,
+
+    /// The working directory the command is spawned in, if not the current one
+    pub working_directory: Option<PathBuf>,
+
+    /// Extra environment variables, in addition to the ones already inherited
+    /// from this process, that the command is spawned with
+    pub environment: Vec<(String, String)>,
+
+    /// Whether to capture stdout/stderr instead of passing the given `Stdio`
+    /// through untouched. Forced on whenever `validation` is set.
+    pub capture_output: bool,
+
+    /// Checks to run against the exit code and/or captured stdout once the
+    /// command has finished
+    pub validation: Option<OutputValidation>,
+}
+
+impl Default for CommandOptions {
+    fn default() -> Self {
+        CommandOptions {
+            shell: Shell::Default,
+            timeout: None,
+            grace_period: DEFAULT_TIMEOUT_GRACE_PERIOD,
+            working_directory: None,
+            environment: Vec::new(),
+            capture_output: false,
+            validation: None,
+        }
+    }
+}
+
+/// Expectations to validate a finished run against.
+#[derive(Debug, Clone)]
+pub struct OutputValidation {
+    /// The exit code the command is expected to return, e.g. via `--expect-exit-code`
+    pub expected_exit_code: Option<i32>,
+
+    /// A pattern the captured stdout is expected to match, e.g. via `--expect-output`
+    pub expected_output: Option<Regex>,
+}
+
+/// Describes why a run failed an `OutputValidation` check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationFailure {
+    /// The exit code did not match `OutputValidation::expected_exit_code`
+    UnexpectedExitCode {
+        expected: i32,
+        actual: Option<i32>,
+    },
+
+    /// The captured stdout did not match `OutputValidation::expected_output`
+    UnexpectedOutput,
+}
+
 /// Used to indicate the result of running a command
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct ExecuteResult {
     /// The amount of user time the process used
     pub user_time: f64,
@@ -15,47 +115,287 @@ pub struct ExecuteResult {
 
     /// The exit status of the process
     pub status: ExitStatus,
+
+    /// Whether the command was killed because it exceeded the `--timeout`
+    pub timed_out: bool,
+
+    /// The captured stdout, if `CommandOptions::capture_output` was set
+    pub stdout: Option<String>,
+
+    /// The captured stderr, if `CommandOptions::capture_output` was set
+    pub stderr: Option<String>,
+
+    /// Set if `CommandOptions::validation` was configured and the run failed it
+    pub validation_failure: Option<ValidationFailure>,
 }
 
 /// Execute the given command and return a timing summary
-#[cfg(windows)]
 pub fn execute_and_time(
     stdout: Stdio,
     stderr: Stdio,
     command: &str,
-    shell: &str,
+    options: &CommandOptions,
 ) -> io::Result<ExecuteResult> {
-    let mut child = run_shell_command(stdout, stderr, command, shell)?;
-    let cpu_timer = get_cpu_timer(&child);
-    let status = child.wait()?;
+    let capture_output = options.capture_output || options.validation.is_some();
+    let (stdout, stderr) = if capture_output {
+        (Stdio::piped(), Stdio::piped())
+    } else {
+        (stdout, stderr)
+    };
+
+    let cpu_timer = get_cpu_timer();
+
+    let mut child = match run_shell_command(stdout, stderr, command, options)? {
+        Some(child) => child,
+        None => {
+            let (user_time, system_time) = cpu_timer.stop();
+            return Ok(ExecuteResult {
+                user_time,
+                system_time,
+                status: empty_exit_status(),
+                timed_out: false,
+                stdout: None,
+                stderr: None,
+                validation_failure: None,
+            });
+        }
+    };
+
+    let output_readers = if capture_output {
+        Some(spawn_output_readers(&mut child))
+    } else {
+        None
+    };
+
+    let (status, timed_out) = wait_with_timeout(&mut child, options.timeout, options.grace_period)?;
+
+    let (captured_stdout, captured_stderr) = match output_readers {
+        Some((stdout_reader, stderr_reader)) => (
+            stdout_reader.map(|handle| handle.join().expect("stdout reader thread panicked")),
+            stderr_reader.map(|handle| handle.join().expect("stderr reader thread panicked")),
+        ),
+        None => (None, None),
+    };
 
     let (user_time, system_time) = cpu_timer.stop();
+
+    let validation_failure = options.validation.as_ref().and_then(|validation| {
+        validate(&status, captured_stdout.as_deref(), validation)
+    });
+
     Ok(ExecuteResult {
         user_time,
         system_time,
         status,
+        timed_out,
+        stdout: captured_stdout,
+        stderr: captured_stderr,
+        validation_failure,
     })
 }
 
-/// Execute the given command and return a timing summary
+/// Spawn one reader thread per pipe so that stdout and stderr are drained
+/// concurrently with the child running (and with each other) - reading them
+/// sequentially after the child exits would deadlock a child that fills one
+/// pipe's buffer before the other closes. The handles are joined only after
+/// the child has been waited on (or killed), so a `--timeout` still takes
+/// effect for a command that never stops producing output.
+fn spawn_output_readers(
+    child: &mut Child,
+) -> (
+    Option<thread::JoinHandle<String>>,
+    Option<thread::JoinHandle<String>>,
+) {
+    let stdout_reader = child.stdout.take().map(|mut pipe| {
+        thread::spawn(move || {
+            let mut buffer = String::new();
+            let _ = pipe.read_to_string(&mut buffer);
+            buffer
+        })
+    });
+    let stderr_reader = child.stderr.take().map(|mut pipe| {
+        thread::spawn(move || {
+            let mut buffer = String::new();
+            let _ = pipe.read_to_string(&mut buffer);
+            buffer
+        })
+    });
+
+    (stdout_reader, stderr_reader)
+}
+
+/// Check a finished run's exit status/captured stdout against an `OutputValidation`
+fn validate(
+    status: &ExitStatus,
+    stdout: Option<&str>,
+    validation: &OutputValidation,
+) -> Option<ValidationFailure> {
+    if let Some(expected) = validation.expected_exit_code {
+        if status.code() != Some(expected) {
+            return Some(ValidationFailure::UnexpectedExitCode {
+                expected,
+                actual: status.code(),
+            });
+        }
+    }
+
+    if let Some(ref expected_output) = validation.expected_output {
+        if !stdout.map_or(false, |stdout| expected_output.is_match(stdout)) {
+            return Some(ValidationFailure::UnexpectedOutput);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+
+    #[cfg(not(windows))]
+    fn exit_status(code: i32) -> ExitStatus {
+        std::os::unix::process::ExitStatusExt::from_raw(code << 8)
+    }
+
+    #[cfg(windows)]
+    fn exit_status(code: i32) -> ExitStatus {
+        std::os::windows::process::ExitStatusExt::from_raw(code as u32)
+    }
+
+    #[test]
+    fn passes_when_nothing_is_expected() {
+        let validation = OutputValidation {
+            expected_exit_code: None,
+            expected_output: None,
+        };
+        assert_eq!(validate(&exit_status(0), None, &validation), None);
+    }
+
+    #[test]
+    fn passes_when_exit_code_matches() {
+        let validation = OutputValidation {
+            expected_exit_code: Some(2),
+            expected_output: None,
+        };
+        assert_eq!(validate(&exit_status(2), None, &validation), None);
+    }
+
+    #[test]
+    fn fails_when_exit_code_does_not_match() {
+        let validation = OutputValidation {
+            expected_exit_code: Some(0),
+            expected_output: None,
+        };
+        assert_eq!(
+            validate(&exit_status(1), None, &validation),
+            Some(ValidationFailure::UnexpectedExitCode {
+                expected: 0,
+                actual: Some(1),
+            })
+        );
+    }
+
+    #[test]
+    fn passes_when_captured_stdout_matches_the_pattern() {
+        let validation = OutputValidation {
+            expected_exit_code: None,
+            expected_output: Some(Regex::new("^ok$").unwrap()),
+        };
+        assert_eq!(validate(&exit_status(0), Some("ok"), &validation), None);
+    }
+
+    #[test]
+    fn fails_when_captured_stdout_does_not_match_the_pattern() {
+        let validation = OutputValidation {
+            expected_exit_code: None,
+            expected_output: Some(Regex::new("^ok$").unwrap()),
+        };
+        assert_eq!(
+            validate(&exit_status(0), Some("fail"), &validation),
+            Some(ValidationFailure::UnexpectedOutput)
+        );
+    }
+
+    #[test]
+    fn fails_when_output_was_not_captured_at_all() {
+        let validation = OutputValidation {
+            expected_exit_code: None,
+            expected_output: Some(Regex::new("^ok$").unwrap()),
+        };
+        assert_eq!(
+            validate(&exit_status(0), None, &validation),
+            Some(ValidationFailure::UnexpectedOutput)
+        );
+    }
+}
+
 #[cfg(not(windows))]
-pub fn execute_and_time(
-    stdout: Stdio,
-    stderr: Stdio,
-    command: &str,
-    shell: &str,
-) -> io::Result<ExecuteResult> {
-    let cpu_timer = get_cpu_timer();
+fn empty_exit_status() -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(0)
+}
 
-    let status = run_shell_command(stdout, stderr, command, shell)?;
+#[cfg(windows)]
+fn empty_exit_status() -> ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(0)
+}
 
-    let (user_time, system_time) = cpu_timer.stop();
+/// Wait for `child` to exit, killing it once `timeout` elapses.
+///
+/// The child is first asked to terminate gracefully (`SIGTERM` on unix,
+/// `TerminateProcess` on Windows) and is given `grace_period` to do so
+/// before being killed outright.
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    grace_period: Duration,
+) -> io::Result<(ExitStatus, bool)> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok((child.wait()?, false)),
+    };
 
-    Ok(ExecuteResult {
-        user_time,
-        system_time,
-        status,
-    })
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if start.elapsed() >= timeout {
+            break;
+        }
+        std::thread::sleep(WAIT_POLL_INTERVAL);
+    }
+
+    terminate_gracefully(child)?;
+
+    let grace_deadline = Instant::now() + grace_period;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, true));
+        }
+        if Instant::now() >= grace_deadline {
+            break;
+        }
+        std::thread::sleep(WAIT_POLL_INTERVAL);
+    }
+
+    child.kill()?;
+    Ok((child.wait()?, true))
+}
+
+#[cfg(not(windows))]
+fn terminate_gracefully(child: &Child) -> io::Result<()> {
+    let result = unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn terminate_gracefully(child: &Child) -> io::Result<()> {
+    // Windows has no graceful-termination signal equivalent to `SIGTERM`, so
+    // we go straight to `TerminateProcess` via `Child::kill`.
+    child.kill()
 }
 
 /// Run a standard shell command using `sh -c`
@@ -64,23 +404,27 @@ fn run_shell_command(
     stdout: Stdio,
     stderr: Stdio,
     command: &str,
-    shell: &str,
-) -> io::Result<std::process::ExitStatus> {
-    let (executable, arguments) = if let Some(command) = prepare_shell_command(command, shell, "-c")? {
+    options: &CommandOptions,
+) -> io::Result<Option<Child>> {
+    let (executable, arguments) = if let Some(command) = prepare_shell_command(command, &options.shell)? {
         command
     } else {
-        return Ok (std::os::unix::process::ExitStatusExt::from_raw(0));
+        return Ok(None);
     };
-    Command::new(executable)
-        .args(arguments)
+    let mut cmd = Command::new(executable);
+    cmd.args(arguments)
         .env(
             "HYPERFINE_RANDOMIZED_ENVIRONMENT_OFFSET",
             "X".repeat(rand::random::<usize>() % 4096usize),
         )
+        .envs(options.environment.iter().map(|(k, v)| (k, v)))
         .stdin(Stdio::null())
         .stdout(stdout)
-        .stderr(stderr)
-        .status()
+        .stderr(stderr);
+    if let Some(ref working_directory) = options.working_directory {
+        cmd.current_dir(working_directory);
+    }
+    cmd.spawn().map(Some)
 }
 
 /// Run a Windows shell command using `cmd.exe /C`
@@ -89,44 +433,121 @@ fn run_shell_command(
     stdout: Stdio,
     stderr: Stdio,
     command: &str,
-    shell: &str,
-) -> io::Result<std::process::Child> {
-    let (executable, arguments) = if let Some(command) = prepare_shell_command(command, shell, "/C")? {
+    options: &CommandOptions,
+) -> io::Result<Option<Child>> {
+    let (executable, arguments) = if let Some(command) = prepare_shell_command(command, &options.shell)? {
         command
     } else {
-        return Ok (std::os::windows::process::ExitStatusExt::from_raw(0));
+        return Ok(None);
     };
-    Command::new(executable)
-        .args(arguments)
+    let mut cmd = Command::new(executable);
+    cmd.args(arguments)
+        .envs(options.environment.iter().map(|(k, v)| (k, v)))
         .stdin(Stdio::null())
         .stdout(stdout)
-        .stderr(stderr)
-        .spawn()
+        .stderr(stderr);
+    if let Some(ref working_directory) = options.working_directory {
+        cmd.current_dir(working_directory);
+    }
+    cmd.spawn().map(Some)
+}
+
+#[cfg(not(windows))]
+fn default_shell_invocation() -> (&'static str, &'static str) {
+    ("sh", "-c")
+}
+
+#[cfg(windows)]
+fn default_shell_invocation() -> (&'static str, &'static str) {
+    ("cmd", "/C")
 }
 
 fn prepare_shell_command(
     command: &str,
-    shell: &str,
-    shell_arg: &str,
+    shell: &Shell,
 ) -> io::Result<Option<(String, Vec<String>)>> {
-    if shell == "" {
-        let mut tokens = match shell_words::split(command) {
-            Ok(tokens) => tokens.into_iter(),
-            Err(error) => return Err(io::Error::new(io::ErrorKind::Other, format!("{}", error))),
-        };
-        if let Some(token) = tokens.next() {
+    match shell {
+        Shell::None => {
+            let mut tokens = match shell_words::split(command) {
+                Ok(tokens) => tokens.into_iter(),
+                Err(error) => return Err(io::Error::new(io::ErrorKind::Other, format!("{}", error))),
+            };
+            if let Some(token) = tokens.next() {
+                Ok(Some((
+                        String::from(token),
+                        tokens.map(String::from).collect(),
+                    )))
+            } else {
+                Ok(None)
+            }
+        }
+        Shell::Default => {
+            let (program, shell_arg) = default_shell_invocation();
             Ok(Some((
-                    String::from(token),
-                    tokens.map(String::from).collect(),
+                    String::from(program),
+                    vec![String::from(shell_arg), String::from(command)],
                 )))
-        } else {
-            Ok(None)
         }
-    } else {
-        Ok(Some((
-                String::from(shell),
-                vec![String::from(shell_arg), String::from(command)],
-            )))
+        Shell::Custom { program, args } => {
+            let mut arguments = args.clone();
+            arguments.push(String::from(command));
+            Ok(Some((program.clone(), arguments)))
+        }
     }
 }
 
+#[cfg(test)]
+mod shell_tests {
+    use super::*;
+
+    #[test]
+    fn none_tokenizes_the_command_directly() {
+        let result = prepare_shell_command("echo hello world", &Shell::None).unwrap();
+        assert_eq!(
+            result,
+            Some((
+                String::from("echo"),
+                vec![String::from("hello"), String::from("world")]
+            ))
+        );
+    }
+
+    #[test]
+    fn none_with_an_empty_command_is_a_no_op() {
+        let result = prepare_shell_command("", &Shell::None).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn default_wraps_the_command_with_the_platform_shell() {
+        let (program, shell_arg) = default_shell_invocation();
+        let result = prepare_shell_command("echo hello", &Shell::Default).unwrap();
+        assert_eq!(
+            result,
+            Some((
+                String::from(program),
+                vec![String::from(shell_arg), String::from("echo hello")]
+            ))
+        );
+    }
+
+    #[test]
+    fn custom_appends_the_command_after_the_configured_args() {
+        let shell = Shell::Custom {
+            program: String::from("pwsh"),
+            args: vec![String::from("-NoProfile"), String::from("-Command")],
+        };
+        let result = prepare_shell_command("Get-Date", &shell).unwrap();
+        assert_eq!(
+            result,
+            Some((
+                String::from("pwsh"),
+                vec![
+                    String::from("-NoProfile"),
+                    String::from("-Command"),
+                    String::from("Get-Date"),
+                ]
+            ))
+        );
+    }
+}
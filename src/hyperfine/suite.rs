@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::hyperfine::shell::{execute_and_time, CommandOptions, ExecuteResult, OutputValidation, Shell};
+
+/// A whole benchmark suite loaded from a TOML or YAML config file: a list of
+/// named commands, each with its own shell, working directory, environment,
+/// timeout, hooks and parameter lists. Keeping this in a file that gets
+/// checked into version control makes large comparison suites reproducible
+/// instead of living in shell history.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SuiteConfig {
+    #[serde(default)]
+    pub commands: Vec<CommandConfig>,
+}
+
+/// One named command of a `SuiteConfig`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandConfig {
+    /// The name this command is reported under
+    pub name: String,
+
+    /// The command line to benchmark
+    pub command: String,
+
+    /// The shell used to invoke `command`
+    #[serde(default)]
+    pub shell: ShellConfig,
+
+    /// The working directory `command` is run in, if not the current one
+    #[serde(default)]
+    pub working_directory: Option<PathBuf>,
+
+    /// Extra environment variables `command` is run with
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+
+    /// The maximum number of seconds a single run is allowed to take
+    #[serde(default)]
+    pub timeout: Option<f64>,
+
+    /// How many seconds a timed-out run is given to exit gracefully before
+    /// being forcibly killed. Defaults to `CommandOptions::default`'s grace
+    /// period if not set.
+    #[serde(default)]
+    pub grace_period: Option<f64>,
+
+    /// A command run once before each timed run of `command`
+    #[serde(default)]
+    pub prepare: Option<String>,
+
+    /// A command run once after each timed run of `command`
+    #[serde(default)]
+    pub cleanup: Option<String>,
+
+    /// Named parameter lists, each value substituted into `command` in turn
+    #[serde(default)]
+    pub parameters: HashMap<String, Vec<String>>,
+
+    /// The exit code `command` is expected to return
+    #[serde(default)]
+    pub expect_exit_code: Option<i32>,
+
+    /// A pattern the captured stdout of `command` is expected to match
+    #[serde(default)]
+    pub expect_output: Option<String>,
+}
+
+/// The on-disk representation of a `Shell`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum ShellConfig {
+    None,
+    Default,
+    Custom { program: String, args: Vec<String> },
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        ShellConfig::Default
+    }
+}
+
+impl From<ShellConfig> for Shell {
+    fn from(config: ShellConfig) -> Self {
+        match config {
+            ShellConfig::None => Shell::None,
+            ShellConfig::Default => Shell::Default,
+            ShellConfig::Custom { program, args } => Shell::Custom { program, args },
+        }
+    }
+}
+
+/// One concrete, fully-substituted command to benchmark, produced by
+/// expanding a `CommandConfig`'s `parameters` against its `command`,
+/// `prepare` and `cleanup` templates.
+#[derive(Debug, Clone)]
+pub struct ExpandedCommand {
+    /// The name this command is reported under
+    pub name: String,
+
+    /// The command line to benchmark, with parameters substituted in
+    pub command: String,
+
+    /// The prepare command, with parameters substituted in
+    pub prepare: Option<String>,
+
+    /// The cleanup command, with parameters substituted in
+    pub cleanup: Option<String>,
+
+    /// The parameter values used to produce `command`/`prepare`/`cleanup`
+    pub parameter_values: HashMap<String, String>,
+
+    /// The options this command should be benchmarked with
+    pub options: CommandOptions,
+}
+
+impl CommandConfig {
+    /// Build the `CommandOptions` this entry should be benchmarked with
+    pub fn to_command_options(&self) -> io::Result<CommandOptions> {
+        let expected_output = match &self.expect_output {
+            Some(pattern) => Some(
+                Regex::new(pattern)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, format!("{}", error)))?,
+            ),
+            None => None,
+        };
+
+        let validation = if self.expect_exit_code.is_some() || expected_output.is_some() {
+            Some(OutputValidation {
+                expected_exit_code: self.expect_exit_code,
+                expected_output,
+            })
+        } else {
+            None
+        };
+
+        let timeout = match self.timeout {
+            Some(seconds) => Some(parse_positive_duration(&self.name, "timeout", seconds)?),
+            None => None,
+        };
+
+        let grace_period = match self.grace_period {
+            Some(seconds) => parse_positive_duration(&self.name, "grace_period", seconds)?,
+            None => CommandOptions::default().grace_period,
+        };
+
+        Ok(CommandOptions {
+            shell: self.shell.clone().into(),
+            timeout,
+            grace_period,
+            working_directory: self.working_directory.clone(),
+            environment: self
+                .environment
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            capture_output: validation.is_some(),
+            validation,
+        })
+    }
+
+    /// Expand this entry into one `ExpandedCommand` per combination of its
+    /// `parameters` (the cartesian product of all parameter lists), or a
+    /// single unexpanded `ExpandedCommand` if it declares no parameters.
+    ///
+    /// A parameter declared with an empty value list is rejected outright,
+    /// rather than being treated the same as "no parameters declared" -
+    /// otherwise `command`/`prepare`/`cleanup` would be emitted unexpanded
+    /// with a dangling `{name}` placeholder still in them.
+    pub fn expand(&self) -> io::Result<Vec<ExpandedCommand>> {
+        if let Some(name) = self
+            .parameters
+            .iter()
+            .find(|(_, values)| values.is_empty())
+            .map(|(name, _)| name)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "parameter '{}' of command '{}' has an empty value list",
+                    name, self.name
+                ),
+            ));
+        }
+
+        let options = self.to_command_options()?;
+
+        Ok(parameter_combinations(&self.parameters)
+            .into_iter()
+            .map(|values| ExpandedCommand {
+                name: self.name.clone(),
+                command: substitute_parameters(&self.command, &values),
+                prepare: self.prepare.as_deref().map(|template| substitute_parameters(template, &values)),
+                cleanup: self.cleanup.as_deref().map(|template| substitute_parameters(template, &values)),
+                parameter_values: values,
+                options: options.clone(),
+            })
+            .collect())
+    }
+}
+
+/// The outcome of benchmarking one `ExpandedCommand`: its `prepare`/`cleanup`
+/// hook runs (if any were configured) alongside the timed run itself.
+#[derive(Debug, Clone)]
+pub struct SuiteRunResult {
+    /// The expanded command this result is for
+    pub command: ExpandedCommand,
+
+    /// The result of running `command.prepare`, if set
+    pub prepare_result: Option<ExecuteResult>,
+
+    /// The result of the timed, validated run of `command.command`
+    pub result: ExecuteResult,
+
+    /// The result of running `command.cleanup`, if set
+    pub cleanup_result: Option<ExecuteResult>,
+}
+
+/// Run every command of `suite`: expand each `CommandConfig`'s parameters,
+/// then feed every resulting `ExpandedCommand` into `execute_and_time`,
+/// running its `prepare`/`cleanup` hooks (if set) around the timed run.
+pub fn run_suite(suite: &SuiteConfig) -> io::Result<Vec<SuiteRunResult>> {
+    let mut results = Vec::new();
+    for command_config in &suite.commands {
+        for expanded in command_config.expand()? {
+            results.push(run_expanded_command(&expanded)?);
+        }
+    }
+    Ok(results)
+}
+
+/// Run a single `ExpandedCommand`'s `prepare` hook (if any), then the
+/// benchmarked command itself, then its `cleanup` hook (if any).
+fn run_expanded_command(command: &ExpandedCommand) -> io::Result<SuiteRunResult> {
+    // Hooks share the benchmarked command's shell/working directory/
+    // environment/timeout, but are not subject to its output validation.
+    let hook_options = CommandOptions {
+        validation: None,
+        capture_output: false,
+        ..command.options.clone()
+    };
+
+    let prepare_result = match &command.prepare {
+        Some(prepare_command) => Some(execute_and_time(
+            Stdio::null(),
+            Stdio::null(),
+            prepare_command,
+            &hook_options,
+        )?),
+        None => None,
+    };
+
+    let result = execute_and_time(Stdio::null(), Stdio::null(), &command.command, &command.options)?;
+
+    let cleanup_result = match &command.cleanup {
+        Some(cleanup_command) => Some(execute_and_time(
+            Stdio::null(),
+            Stdio::null(),
+            cleanup_command,
+            &hook_options,
+        )?),
+        None => None,
+    };
+
+    Ok(SuiteRunResult {
+        command: command.clone(),
+        prepare_result,
+        result,
+        cleanup_result,
+    })
+}
+
+/// Parse a config field given in (fractional) seconds into a `Duration`,
+/// rejecting negative, infinite or NaN values with a descriptive error.
+fn parse_positive_duration(command_name: &str, field: &str, seconds: f64) -> io::Result<Duration> {
+    if seconds.is_finite() && seconds >= 0.0 {
+        Ok(Duration::from_secs_f64(seconds))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid {} for command '{}': {}", field, command_name, seconds),
+        ))
+    }
+}
+
+/// Replace each `{name}` placeholder in `template` with its parameter value,
+/// in a single pass over `template`. Substitutions are never themselves
+/// re-scanned for further placeholders, so a value that happens to contain
+/// `{other_name}` is inserted verbatim rather than being expanded again.
+fn substitute_parameters(template: &str, values: &HashMap<String, String>) -> String {
+    let placeholder = Regex::new(r"\{([A-Za-z0-9_]+)\}").expect("placeholder pattern is valid");
+
+    placeholder
+        .replace_all(template, |captures: &regex::Captures| {
+            values
+                .get(&captures[1])
+                .cloned()
+                .unwrap_or_else(|| captures[0].to_string())
+        })
+        .into_owned()
+}
+
+/// The cartesian product of a set of named parameter lists, e.g.
+/// `{"size": ["1", "2"]}` becomes `[{"size": "1"}, {"size": "2"}]`. Returns
+/// a single empty combination if `parameters` itself is empty, so the result
+/// is never empty (callers must reject empty *value lists* beforehand).
+fn parameter_combinations(parameters: &HashMap<String, Vec<String>>) -> Vec<HashMap<String, String>> {
+    let mut names: Vec<&String> = parameters.keys().collect();
+    names.sort();
+
+    let mut combinations: Vec<HashMap<String, String>> = vec![HashMap::new()];
+    for name in &names {
+        let values = &parameters[*name];
+        let mut expanded = Vec::with_capacity(combinations.len() * values.len());
+        for combination in &combinations {
+            for value in values {
+                let mut extended = combination.clone();
+                extended.insert((*name).clone(), value.clone());
+                expanded.push(extended);
+            }
+        }
+        combinations = expanded;
+    }
+
+    combinations
+}
+
+/// Load a `SuiteConfig` from a TOML or YAML file, the format being chosen
+/// based on the file's extension (`.yaml`/`.yml` for YAML, anything else
+/// assumed to be TOML).
+pub fn load_suite(path: &Path) -> io::Result<SuiteConfig> {
+    let contents = fs::read_to_string(path)?;
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{}", error))),
+        _ => toml::from_str(&contents)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{}", error))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters(pairs: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        pairs
+            .iter()
+            .map(|(name, values)| {
+                (
+                    String::from(*name),
+                    values.iter().map(|value| String::from(*value)).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parameter_combinations_of_empty_map_is_single_empty_combination() {
+        let combinations = parameter_combinations(&HashMap::new());
+        assert_eq!(combinations, vec![HashMap::new()]);
+    }
+
+    #[test]
+    fn parameter_combinations_single_key() {
+        let combinations = parameter_combinations(&parameters(&[("size", &["1", "2"])]));
+
+        let mut first = HashMap::new();
+        first.insert(String::from("size"), String::from("1"));
+        let mut second = HashMap::new();
+        second.insert(String::from("size"), String::from("2"));
+
+        assert_eq!(combinations, vec![first, second]);
+    }
+
+    #[test]
+    fn parameter_combinations_multi_key_is_cartesian_product_in_sorted_key_order() {
+        let combinations =
+            parameter_combinations(&parameters(&[("b", &["x"]), ("a", &["1", "2"])]));
+
+        let expected = vec![
+            [("a", "1"), ("b", "x")]
+                .iter()
+                .map(|(k, v)| (String::from(*k), String::from(*v)))
+                .collect::<HashMap<_, _>>(),
+            [("a", "2"), ("b", "x")]
+                .iter()
+                .map(|(k, v)| (String::from(*k), String::from(*v)))
+                .collect::<HashMap<_, _>>(),
+        ];
+
+        assert_eq!(combinations, expected);
+    }
+
+    #[test]
+    fn substitute_parameters_replaces_known_placeholders() {
+        let mut values = HashMap::new();
+        values.insert(String::from("size"), String::from("42"));
+
+        assert_eq!(
+            substitute_parameters("run --size={size}", &values),
+            "run --size=42"
+        );
+    }
+
+    #[test]
+    fn substitute_parameters_leaves_unknown_placeholders_untouched() {
+        let values = HashMap::new();
+        assert_eq!(substitute_parameters("echo {missing}", &values), "echo {missing}");
+    }
+
+    #[test]
+    fn substitute_parameters_does_not_cascade_into_inserted_values() {
+        // A value that itself looks like a placeholder (here "{b}", the
+        // literal text) must not be expanded further, regardless of
+        // HashMap iteration order.
+        let mut values = HashMap::new();
+        values.insert(String::from("a"), String::from("{b}"));
+        values.insert(String::from("b"), String::from("x"));
+
+        assert_eq!(substitute_parameters("{a}-{b}", &values), "{b}-x");
+    }
+
+    fn minimal_config(name: &str, command: &str) -> CommandConfig {
+        CommandConfig {
+            name: String::from(name),
+            command: String::from(command),
+            shell: ShellConfig::Default,
+            working_directory: None,
+            environment: HashMap::new(),
+            timeout: None,
+            grace_period: None,
+            prepare: None,
+            cleanup: None,
+            parameters: HashMap::new(),
+            expect_exit_code: None,
+            expect_output: None,
+        }
+    }
+
+    #[test]
+    fn expand_without_parameters_returns_single_unsubstituted_command() {
+        let config = minimal_config("noop", "true");
+
+        let expanded = config.expand().unwrap();
+        assert_eq!(expanded.len(), 1);
+        assert_eq!(expanded[0].command, "true");
+        assert!(expanded[0].parameter_values.is_empty());
+    }
+
+    #[test]
+    fn expand_rejects_empty_parameter_value_list() {
+        let mut config = minimal_config("broken", "echo {size}");
+        config.parameters = parameters(&[("size", &[])]);
+
+        assert!(config.expand().is_err());
+    }
+
+    #[test]
+    fn expand_with_parameters_substitutes_each_combination() {
+        let mut config = minimal_config("bench", "run --size={size}");
+        config.parameters = parameters(&[("size", &["1", "2"])]);
+
+        let expanded = config.expand().unwrap();
+        let commands: Vec<&str> = expanded.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(commands, vec!["run --size=1", "run --size=2"]);
+    }
+
+    #[test]
+    fn to_command_options_rejects_non_finite_timeout() {
+        let mut config = minimal_config("bad-timeout", "true");
+        config.timeout = Some(f64::NAN);
+
+        assert!(config.to_command_options().is_err());
+    }
+
+    #[test]
+    fn to_command_options_rejects_negative_timeout() {
+        let mut config = minimal_config("bad-timeout", "true");
+        config.timeout = Some(-1.0);
+
+        assert!(config.to_command_options().is_err());
+    }
+
+    #[test]
+    fn to_command_options_rejects_invalid_expect_output_regex() {
+        let mut config = minimal_config("bad-regex", "true");
+        config.expect_output = Some(String::from("("));
+
+        assert!(config.to_command_options().is_err());
+    }
+
+    #[test]
+    fn to_command_options_accepts_valid_timeout_and_regex() {
+        let mut config = minimal_config("good", "true");
+        config.timeout = Some(1.5);
+        config.expect_output = Some(String::from("^ok$"));
+        config.expect_exit_code = Some(0);
+
+        let options = config.to_command_options().unwrap();
+        assert_eq!(options.timeout, Some(Duration::from_secs_f64(1.5)));
+        assert!(options.capture_output);
+        assert!(options.validation.is_some());
+    }
+
+    #[test]
+    fn load_suite_parses_toml() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("hyperfine-suite-test.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [[commands]]
+            name = "sleep"
+            command = "sleep 0.1"
+            working_directory = "/tmp"
+
+            [commands.environment]
+            FOO = "bar"
+
+            [commands.shell]
+            kind = "custom"
+            program = "bash"
+            args = ["--norc", "-c"]
+            "#,
+        )
+        .unwrap();
+
+        let suite = load_suite(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(suite.commands.len(), 1);
+        let command = &suite.commands[0];
+        assert_eq!(command.name, "sleep");
+        assert_eq!(command.command, "sleep 0.1");
+        assert_eq!(command.environment.get("FOO"), Some(&String::from("bar")));
+        assert_eq!(
+            command.shell,
+            ShellConfig::Custom {
+                program: String::from("bash"),
+                args: vec![String::from("--norc"), String::from("-c")],
+            }
+        );
+    }
+
+    #[test]
+    fn load_suite_parses_yaml() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("hyperfine-suite-test.yaml");
+        std::fs::write(
+            &path,
+            r#"
+commands:
+  - name: sleep
+    command: sleep 0.1
+    shell:
+      kind: default
+"#,
+        )
+        .unwrap();
+
+        let suite = load_suite(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(suite.commands.len(), 1);
+        assert_eq!(suite.commands[0].name, "sleep");
+        assert_eq!(suite.commands[0].shell, ShellConfig::Default);
+    }
+}